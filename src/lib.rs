@@ -2,6 +2,8 @@
 //!
 //! You can convert locally or on a server running comiconv-server.
 
+mod preview;
+
 use cra::{ArcEntry, ArcError, ArcReader, ArcWriter};
 use image::{
     codecs::{
@@ -9,23 +11,32 @@ use image::{
         png::{CompressionType, FilterType, PngEncoder},
         webp::WebPEncoder,
     },
+    imageops::FilterType as ResizeFilter,
     ColorType, DynamicImage, ImageError, ImageReader,
 };
 use indicatif::{style::TemplateError, ProgressBar, ProgressStyle};
 use infer::image::is_jxl;
 use jxl_oxide::integration::JxlDecoder;
 use libavif_image::{is_avif, read as read_avif, save as save_avif, Error as AvifError};
-use rayon::prelude::{IntoParallelIterator, ParallelIterator};
 use sha2::{Digest, Sha256};
 use std::{
-    fs::{rename, File},
+    collections::HashMap,
+    env,
+    fs::{create_dir_all, read, remove_dir_all, remove_file, rename, write, File},
     io::{self, Cursor, Read, Write},
     net::TcpStream,
+    path::{Path, PathBuf},
+    process,
     str::FromStr,
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread,
     time::Duration,
 };
 use thiserror::Error;
+use tiff::encoder::{colortype, compression, TiffEncoder};
 use zune_core::{bit_depth::BitDepth, colorspace::ColorSpace, options::EncoderOptions};
 use zune_jpegxl::{JxlEncodeErrors, JxlSimpleEncoder};
 
@@ -38,6 +49,8 @@ pub enum ConvError {
     TemplateError(#[from] TemplateError),
     AvifError(#[from] AvifError),
     ImageError(#[from] ImageError),
+    OxipngError(#[from] oxipng::PngError),
+    TiffError(#[from] tiff::TiffError),
     #[error("{0:?}")]
     JxlEncodeError(JxlEncodeErrors),
     #[error("Invalid server response")]
@@ -48,6 +61,13 @@ pub enum ConvError {
 
 pub type ConvResult<T> = Result<T, ConvError>;
 
+/// Longest side, in pixels, of the cover thumbnail generated when `Converter::preview` is set.
+const THUMBNAIL_MAX_DIMENSION: u32 = 256;
+
+/// Disambiguates scratch directories between concurrent `convert()` calls in the same process
+/// (e.g. a server handling several conversions on separate threads), since those all share a pid.
+static CONVERSION_COUNTER: AtomicU64 = AtomicU64::new(0);
+
 /// Enum representing all supported target image formats
 #[derive(Clone, Copy, Debug)]
 pub enum Format {
@@ -56,6 +76,7 @@ pub enum Format {
     Png,
     Webp,
     Avif,
+    Tiff,
 }
 
 impl ToString for Format {
@@ -66,6 +87,7 @@ impl ToString for Format {
             Format::Png => "png",
             Format::Webp => "webp",
             Format::Avif => "avif",
+            Format::Tiff => "tiff",
         })
     }
 }
@@ -80,11 +102,47 @@ impl FromStr for Format {
             "jxl" => Ok(Format::JpegXL),
             "webp" => Ok(Format::Webp),
             "png" => Ok(Format::Png),
+            "tiff" | "tif" => Ok(Format::Tiff),
             _ => Err(format!("Invalid format: {s}")),
         }
     }
 }
 
+/// Compression scheme used when encoding to `Format::Tiff`.
+#[derive(Clone, Copy, Debug, Default)]
+pub enum TiffCompression {
+    Uncompressed,
+    PackBits,
+    Lzw,
+    #[default]
+    Deflate,
+}
+
+impl ToString for TiffCompression {
+    fn to_string(&self) -> String {
+        String::from(match self {
+            TiffCompression::Uncompressed => "uncompressed",
+            TiffCompression::PackBits => "packbits",
+            TiffCompression::Lzw => "lzw",
+            TiffCompression::Deflate => "deflate",
+        })
+    }
+}
+
+impl FromStr for TiffCompression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "uncompressed" | "none" => Ok(TiffCompression::Uncompressed),
+            "packbits" => Ok(TiffCompression::PackBits),
+            "lzw" => Ok(TiffCompression::Lzw),
+            "deflate" => Ok(TiffCompression::Deflate),
+            _ => Err(format!("Invalid TIFF compression: {s}")),
+        }
+    }
+}
+
 /// This is the main struct for converting
 /// `quality` is ignored for webp
 #[derive(Clone, Copy, Debug)]
@@ -94,6 +152,19 @@ pub struct Converter {
     pub format: Format,
     pub backup: bool,
     pub quiet: bool,
+    /// Run an oxipng lossless optimization pass over PNG output. Ignored for other formats.
+    pub optimize: bool,
+    /// Number of pages decoded and re-encoded at the same time during `convert`. Bounds how many
+    /// decoded pages are held in RAM at once; the re-encoded output is still kept in memory.
+    pub max_inflight: usize,
+    /// Generate a `preview.json` (per-page BlurHash) and a `thumbnail.<format>` of the cover
+    /// page, added as extra entries inside the converted archive.
+    pub preview: bool,
+    /// If set, pages wider or taller than this are proportionally downscaled (Lanczos3, never
+    /// upscaled) before encoding. Useful for shrinking oversized scans for e-reader targets.
+    pub max_dimension: Option<u32>,
+    /// Compression scheme used when `format` is `Format::Tiff`. Ignored otherwise.
+    pub tiff_compression: TiffCompression,
 }
 
 impl Default for Converter {
@@ -104,6 +175,11 @@ impl Default for Converter {
             format: Format::Avif,
             backup: false,
             quiet: false,
+            optimize: false,
+            max_inflight: 4,
+            preview: false,
+            max_dimension: None,
+            tiff_compression: TiffCompression::default(),
         }
     }
 }
@@ -176,33 +252,133 @@ impl Converter {
         );
         let status_stream = status_stream.map(|stream| Arc::new(Mutex::new(stream)));
         let pb = Arc::new(Mutex::new(&mut bar));
-        writer.extend(
-            &archive
-                .entries()
-                .clone()
-                .into_par_iter()
-                .map(|entry| {
-                    Ok(match entry {
-                        ArcEntry::File(name, data) => {
-                            let data = self.convert_image(&data)?;
-                            if let Some(stream) = status_stream.clone() {
-                                stream.lock().unwrap().write_all(b"plus")?
+
+        // Bound the number of decoded pages: only `max_inflight` pages are decoded/re-encoded at
+        // once, and a page that finishes out of turn is spilled to a scratch file until its turn
+        // comes. The input entries and everything written into `writer` are still held in RAM,
+        // so memory still grows with the archive size. Original page order is restored as
+        // entries are written into `writer`.
+        let max_inflight = self.max_inflight.max(1);
+        let entries = archive.entries().clone();
+        let first_file_index = entries
+            .iter()
+            .position(|entry| matches!(entry, ArcEntry::File(..)));
+        let work = Arc::new(Mutex::new(entries.into_iter().enumerate()));
+        let (done_tx, done_rx) = mpsc::channel::<ConvResult<WorkResult>>();
+        let conversion_id = CONVERSION_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let scratch_dir = env::temp_dir().join(format!("comiconv-{}-{}", process::id(), conversion_id));
+        create_dir_all(&scratch_dir)?;
+
+        let result = thread::scope(|scope| -> ConvResult<(Vec<preview::PagePreview>, Option<DynamicImage>)> {
+            for _ in 0..max_inflight {
+                let work = work.clone();
+                let done_tx = done_tx.clone();
+                let status_stream = status_stream.clone();
+                let pb = pb.clone();
+                let format_extension = &format_extension;
+                scope.spawn(move || loop {
+                    let Some((index, entry)) = work.lock().unwrap().next() else {
+                        break;
+                    };
+                    let is_file = matches!(entry, ArcEntry::File(..));
+                    let result = (|| -> ConvResult<WorkResult> {
+                        match entry {
+                            ArcEntry::File(name, data) => {
+                                let image = Self::decode_image(&data)?;
+                                let page_preview = self.preview.then(|| preview::PagePreview {
+                                    name: name.clone(),
+                                    blurhash: preview::blurhash(&image),
+                                });
+                                let thumbnail = (self.preview
+                                    && first_file_index == Some(index))
+                                .then(|| preview::thumbnail(&image, THUMBNAIL_MAX_DIMENSION));
+                                let data = self.encode_image(&image)?;
+                                Ok(WorkResult {
+                                    index,
+                                    entry: ArcEntry::File(
+                                        format!(
+                                            "{}.{}",
+                                            name.rsplit_once('.').unwrap_or((&name, "")).0,
+                                            format_extension
+                                        ),
+                                        data,
+                                    ),
+                                    page_preview,
+                                    thumbnail,
+                                })
                             }
-                            pb.clone().lock().unwrap().inc(1);
-                            ArcEntry::File(
-                                format!(
-                                    "{}.{}",
-                                    name.rsplit_once('.').unwrap_or((&name, "")).0,
-                                    &format_extension
-                                ),
-                                data,
-                            )
+                            other => Ok(WorkResult {
+                                index,
+                                entry: other,
+                                page_preview: None,
+                                thumbnail: None,
+                            }),
                         }
-                        other => other,
-                    })
-                })
-                .collect::<ConvResult<Vec<ArcEntry>>>()?,
-        );
+                    })();
+                    if result.is_ok() && is_file {
+                        if let Some(stream) = status_stream.clone() {
+                            let _ = stream.lock().unwrap().write_all(b"plus");
+                        }
+                        pb.lock().unwrap().inc(1);
+                    }
+                    if done_tx.send(result).is_err() {
+                        break;
+                    }
+                });
+            }
+            drop(done_tx);
+
+            let mut reassembler = Reassembler::new(
+                |index, entry| PendingEntry::spill(entry, index, &scratch_dir),
+                PendingEntry::into_entry,
+            );
+            let mut first_error = None;
+            let mut previews = Vec::new();
+            let mut cover_thumbnail = None;
+            for result in done_rx {
+                let work_result = match result {
+                    Ok(work_result) => work_result,
+                    Err(err) => {
+                        first_error.get_or_insert(err);
+                        continue;
+                    }
+                };
+                if let Some(page_preview) = work_result.page_preview {
+                    previews.push((work_result.index, page_preview));
+                }
+                if work_result.thumbnail.is_some() {
+                    cover_thumbnail = work_result.thumbnail;
+                }
+                let ready = reassembler.push(work_result.index, work_result.entry)?;
+                if !ready.is_empty() {
+                    writer.extend(&ready);
+                }
+            }
+            if let Some(err) = first_error {
+                return Err(err);
+            }
+            previews.sort_by_key(|(index, _)| *index);
+            Ok((
+                previews.into_iter().map(|(_, preview)| preview).collect(),
+                cover_thumbnail,
+            ))
+        });
+        remove_dir_all(&scratch_dir).ok();
+        let (previews, cover_thumbnail) = result?;
+
+        if self.preview {
+            writer.extend(&[ArcEntry::File(
+                String::from("preview.json"),
+                preview::manifest(&previews).into_bytes(),
+            )]);
+            if let Some(cover_thumbnail) = cover_thumbnail {
+                writer.extend(&[ArcEntry::File(
+                    format!("thumbnail.{}", format_extension),
+                    self.encode_image(&cover_thumbnail)?,
+                )]);
+            }
+        }
+
         bar.finish();
         Ok(writer.archive()?)
     }
@@ -226,7 +402,8 @@ impl Converter {
             Format::Webp => b'W',
             Format::Png => b'P',
             Format::Jpeg => b'J',
-            Format::JpegXL => todo!(),
+            Format::JpegXL => b'X',
+            Format::Tiff => b'T',
         };
         let mut left = buf.len();
         {
@@ -333,7 +510,11 @@ impl Converter {
     }
 
     fn convert_image(self, buf: &[u8]) -> ConvResult<Vec<u8>> {
-        let image = if is_avif(buf) {
+        self.encode_image(&Self::decode_image(buf)?)
+    }
+
+    fn decode_image(buf: &[u8]) -> ConvResult<DynamicImage> {
+        Ok(if is_avif(buf) {
             read_avif(buf)?
         } else if is_jxl(buf) {
             DynamicImage::from_decoder(JxlDecoder::new(buf)?)?
@@ -341,28 +522,50 @@ impl Converter {
             ImageReader::new(Cursor::new(buf))
                 .with_guessed_format()?
                 .decode()?
+        })
+    }
+
+    fn encode_image(self, image: &DynamicImage) -> ConvResult<Vec<u8>> {
+        let downscaled;
+        let image = match self.max_dimension {
+            Some(max) if image.width() > max || image.height() > max => {
+                downscaled = image.resize(max, max, ResizeFilter::Lanczos3);
+                &downscaled
+            }
+            _ => image,
         };
         let mut data = Vec::new();
         match self.format {
             Format::Avif => {
-                data = save_avif(&image)?.to_vec();
+                data = save_avif(image)?.to_vec();
             }
             Format::Webp => image.write_with_encoder(WebPEncoder::new_lossless(&mut data))?,
-            Format::Png => image.write_with_encoder(PngEncoder::new_with_quality(
-                &mut data,
-                match self.speed.clamp(0, 2) {
-                    0 => CompressionType::Fast,
-                    1 => CompressionType::Default,
-                    2 => CompressionType::Best,
-                    _ => unreachable!(),
-                },
-                FilterType::Adaptive,
-            ))?,
+            Format::Png => {
+                image.write_with_encoder(PngEncoder::new_with_quality(
+                    &mut data,
+                    match self.speed.clamp(0, 2) {
+                        0 => CompressionType::Fast,
+                        1 => CompressionType::Default,
+                        2 => CompressionType::Best,
+                        _ => unreachable!(),
+                    },
+                    FilterType::Adaptive,
+                ))?;
+                if self.optimize {
+                    // Losslessly re-minify the already-valid PNG: bit-depth/color-type/palette
+                    // reduction plus filter and deflate trials, keeping whichever comes out smallest.
+                    data = oxipng::optimize_from_memory(
+                        &data,
+                        &oxipng::Options::from_preset(self.speed.clamp(0, 6)),
+                    )?;
+                }
+            }
             Format::Jpeg => {
                 image.write_with_encoder(JpegEncoder::new_with_quality(&mut data, self.quality))?
             }
+            Format::Tiff => encode_tiff(image, self.tiff_compression, &mut data)?,
             Format::JpegXL => {
-                let (color, depth) = image_to_zune_colot_type(&image);
+                let (color, depth) = image_to_zune_colot_type(image);
                 data = JxlSimpleEncoder::new(
                     image.as_bytes(),
                     EncoderOptions::new(image.width() as _, image.height() as _, color, depth),
@@ -375,6 +578,198 @@ impl Converter {
     }
 }
 
+/// A single worker's output for one archive entry: the re-encoded entry itself, plus whatever
+/// preview data (per-page BlurHash, cover thumbnail) it happened to produce along the way.
+struct WorkResult {
+    index: usize,
+    entry: ArcEntry,
+    page_preview: Option<preview::PagePreview>,
+    thumbnail: Option<DynamicImage>,
+}
+
+/// Restores the original archive order from a worker pool's out-of-order completions. Items that
+/// arrive ahead of their turn are handed to `stash` (e.g. spilled to disk) and held as `S`; when
+/// their turn comes they're brought back via `unstash`. Generic over the stash representation so
+/// it can be unit-tested without going through the filesystem or a real archive entry type.
+struct Reassembler<T, S, Stash, Unstash>
+where
+    Stash: FnMut(usize, T) -> ConvResult<S>,
+    Unstash: FnMut(S) -> ConvResult<T>,
+{
+    next_to_write: usize,
+    pending: HashMap<usize, S>,
+    stash: Stash,
+    unstash: Unstash,
+}
+
+impl<T, S, Stash, Unstash> Reassembler<T, S, Stash, Unstash>
+where
+    Stash: FnMut(usize, T) -> ConvResult<S>,
+    Unstash: FnMut(S) -> ConvResult<T>,
+{
+    fn new(stash: Stash, unstash: Unstash) -> Self {
+        Self {
+            next_to_write: 0,
+            pending: HashMap::new(),
+            stash,
+            unstash,
+        }
+    }
+
+    /// Feeds the entry at `index`, returning the run of now-in-order entries (possibly empty)
+    /// that this completion unblocked, in order.
+    fn push(&mut self, index: usize, item: T) -> ConvResult<Vec<T>> {
+        let mut ready = Vec::new();
+        if index == self.next_to_write {
+            ready.push(item);
+            self.next_to_write += 1;
+            while let Some(stashed) = self.pending.remove(&self.next_to_write) {
+                ready.push((self.unstash)(stashed)?);
+                self.next_to_write += 1;
+            }
+        } else {
+            let stashed = (self.stash)(index, item)?;
+            self.pending.insert(index, stashed);
+        }
+        Ok(ready)
+    }
+}
+
+#[cfg(test)]
+mod reassembler_tests {
+    use super::Reassembler;
+
+    #[test]
+    fn restores_order_from_scrambled_completions() {
+        let mut reassembler = Reassembler::new(|_index, item| Ok(item), Ok);
+
+        let mut written = Vec::new();
+        for (index, item) in [(2, 'c'), (0, 'a'), (1, 'b'), (4, 'e'), (3, 'd')] {
+            written.extend(reassembler.push(index, item).unwrap());
+        }
+
+        assert_eq!(written, vec!['a', 'b', 'c', 'd', 'e']);
+    }
+
+    #[test]
+    fn holds_back_entries_until_their_turn() {
+        let mut reassembler = Reassembler::new(|_index, item| Ok(item), Ok);
+
+        assert_eq!(reassembler.push(1, "b").unwrap(), Vec::<&str>::new());
+        assert_eq!(reassembler.push(2, "c").unwrap(), Vec::<&str>::new());
+        assert_eq!(reassembler.push(0, "a").unwrap(), vec!["a", "b", "c"]);
+    }
+}
+
+/// An archive entry that finished re-encoding before its turn to be written. Files are spilled
+/// to a scratch file so a burst of out-of-order completions can't pile up in memory; directories
+/// carry no data and are simply held as-is.
+enum PendingEntry {
+    Entry(ArcEntry),
+    Spilled { name: String, path: PathBuf },
+}
+
+impl PendingEntry {
+    fn spill(entry: ArcEntry, index: usize, scratch_dir: &Path) -> ConvResult<Self> {
+        Ok(match entry {
+            ArcEntry::File(name, data) => {
+                let path = scratch_dir.join(index.to_string());
+                write(&path, &data)?;
+                PendingEntry::Spilled { name, path }
+            }
+            other => PendingEntry::Entry(other),
+        })
+    }
+
+    fn into_entry(self) -> ConvResult<ArcEntry> {
+        Ok(match self {
+            PendingEntry::Entry(entry) => entry,
+            PendingEntry::Spilled { name, path } => {
+                let data = read(&path)?;
+                remove_file(&path)?;
+                ArcEntry::File(name, data)
+            }
+        })
+    }
+}
+
+fn encode_tiff(image: &DynamicImage, compression: TiffCompression, data: &mut Vec<u8>) -> ConvResult<()> {
+    let rgb = image.to_rgb8();
+    let mut encoder = TiffEncoder::new(Cursor::new(data))?;
+    match compression {
+        TiffCompression::Uncompressed => encoder
+            .new_image::<colortype::RGB8>(rgb.width(), rgb.height())?
+            .write_data(&rgb)?,
+        TiffCompression::PackBits => encoder
+            .new_image_with_compression::<colortype::RGB8, _>(
+                rgb.width(),
+                rgb.height(),
+                compression::Packbits,
+            )?
+            .write_data(&rgb)?,
+        TiffCompression::Lzw => encoder
+            .new_image_with_compression::<colortype::RGB8, _>(
+                rgb.width(),
+                rgb.height(),
+                compression::Lzw,
+            )?
+            .write_data(&rgb)?,
+        TiffCompression::Deflate => encoder
+            .new_image_with_compression::<colortype::RGB8, _>(
+                rgb.width(),
+                rgb.height(),
+                compression::Deflate::default(),
+            )?
+            .write_data(&rgb)?,
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod encode_tests {
+    use super::*;
+    use image::{ImageFormat, Rgb, RgbImage};
+
+    fn sample_image() -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(16, 12, |x, y| {
+            Rgb([(x * 13) as u8, (y * 19) as u8, ((x + y) * 7) as u8])
+        }))
+    }
+
+    #[test]
+    fn oxipng_optimize_pass_is_lossless() {
+        let image = sample_image();
+        let converter = Converter {
+            format: Format::Png,
+            optimize: true,
+            ..Default::default()
+        };
+        let encoded = converter.encode_image(&image).unwrap();
+        let decoded = image::load_from_memory_with_format(&encoded, ImageFormat::Png).unwrap();
+        assert_eq!(decoded.to_rgb8(), image.to_rgb8());
+    }
+
+    #[test]
+    fn tiff_compression_schemes_round_trip_losslessly() {
+        let image = sample_image();
+        for compression in [
+            TiffCompression::Uncompressed,
+            TiffCompression::PackBits,
+            TiffCompression::Lzw,
+            TiffCompression::Deflate,
+        ] {
+            let mut data = Vec::new();
+            encode_tiff(&image, compression, &mut data).unwrap();
+            let decoded = image::load_from_memory_with_format(&data, ImageFormat::Tiff).unwrap();
+            assert_eq!(
+                decoded.to_rgb8(),
+                image.to_rgb8(),
+                "{compression:?} did not round-trip losslessly"
+            );
+        }
+    }
+}
+
 fn image_to_zune_colot_type(image: &DynamicImage) -> (ColorSpace, BitDepth) {
     match image.color() {
         ColorType::L8 => (ColorSpace::Luma, BitDepth::Eight),
@@ -19,10 +19,25 @@ fn main() {
                 .value_parser(value_parser!(u8)),
         )
         .arg(
-            arg!(-f --format <VALUE>"Set format (avif, webp, jpeg, png)")
+            arg!(-f --format <VALUE>"Set format (avif, webp, jpeg, png, jxl, tiff)")
                 .required(false)
                 .value_parser(value_parser!(String)),
         )
+        .arg(
+            arg!(--"tiff-compression" <VALUE> "Set TIFF compression, only used when format is tiff (uncompressed, packbits, lzw, deflate)")
+                .required(false)
+                .value_parser(value_parser!(String)),
+        )
+        .arg(arg!(--optimize "Further optimize with oxipng, only used when format is png").required(false))
+        .arg(
+            arg!(--"max-dimension" <VALUE> "Downscale pages wider or taller than this many pixels (preserves aspect ratio, never upscales)")
+                .required(false)
+                .value_parser(value_parser!(u32)),
+        )
+        .arg(
+            arg!(--preview "Generate a preview.json (per-page BlurHash) and cover thumbnail as extra entries inside the converted archive")
+                .required(false),
+        )
         .arg(arg!(--quiet "Suppress progress messages").required(false))
         .arg(arg!(--backup "Keep backup of original file").required(false))
         .arg(
@@ -39,6 +54,8 @@ fn main() {
     let mut converter = Converter {
         quiet: matches.get_flag("quiet"),
         backup: matches.get_flag("backup"),
+        optimize: matches.get_flag("optimize"),
+        preview: matches.get_flag("preview"),
         ..Default::default()
     };
     if let Some(q) = matches.get_one::<u8>("quality") {
@@ -47,6 +64,12 @@ fn main() {
     if let Some(f) = matches.get_one::<String>("format") {
         converter.format = f.parse().unwrap()
     }
+    if let Some(c) = matches.get_one::<String>("tiff-compression") {
+        converter.tiff_compression = c.parse().unwrap()
+    }
+    if let Some(d) = matches.get_one::<u32>("max-dimension") {
+        converter.max_dimension = Some(*d)
+    }
     if let Some(s) = matches.get_one::<u8>("speed") {
         converter.speed = *s
     }
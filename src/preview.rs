@@ -0,0 +1,187 @@
+//! Per-archive preview data: a small thumbnail of the cover page and a compact BlurHash string
+//! for every page, so a client can show a placeholder without decoding any page itself.
+
+use image::{imageops::FilterType, DynamicImage, GenericImageView};
+use std::f64::consts::PI;
+
+/// Number of horizontal/vertical BlurHash components. 4x3 matches the reference implementation's
+/// default and keeps the hash short while still capturing the page's dominant shapes and colors.
+const COMPONENTS_X: u32 = 4;
+const COMPONENTS_Y: u32 = 3;
+
+const BASE83_CHARS: &[u8] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz#$%*+,-.:;=?@[]^_{|}~";
+
+/// One page's entry in the preview manifest.
+#[derive(Debug, Clone)]
+pub struct PagePreview {
+    pub name: String,
+    pub blurhash: String,
+}
+
+/// Downscales `image` to fit within `max_dimension` on its longest side, preserving aspect
+/// ratio. Never upscales.
+pub fn thumbnail(image: &DynamicImage, max_dimension: u32) -> DynamicImage {
+    let (width, height) = image.dimensions();
+    if width <= max_dimension && height <= max_dimension {
+        return image.clone();
+    }
+    image.resize(max_dimension, max_dimension, FilterType::Lanczos3)
+}
+
+/// Encodes `image` as a BlurHash string: a DC (average color) component plus `COMPONENTS_X *
+/// COMPONENTS_Y - 1` low-frequency AC components, quantized into base83.
+pub fn blurhash(image: &DynamicImage) -> String {
+    let (width, height) = image.dimensions();
+    let rgb = image.to_rgb8();
+
+    let mut factors = vec![[0.0f64; 3]; (COMPONENTS_X * COMPONENTS_Y) as usize];
+    for y in 0..height {
+        for x in 0..width {
+            let pixel = rgb.get_pixel(x, y);
+            let linear = [
+                srgb_to_linear(pixel[0]),
+                srgb_to_linear(pixel[1]),
+                srgb_to_linear(pixel[2]),
+            ];
+            for j in 0..COMPONENTS_Y {
+                for i in 0..COMPONENTS_X {
+                    let basis = (PI * i as f64 * x as f64 / width as f64).cos()
+                        * (PI * j as f64 * y as f64 / height as f64).cos();
+                    let factor = &mut factors[(i + j * COMPONENTS_X) as usize];
+                    factor[0] += basis * linear[0];
+                    factor[1] += basis * linear[1];
+                    factor[2] += basis * linear[2];
+                }
+            }
+        }
+    }
+    let pixel_count = (width * height) as f64;
+    for (index, factor) in factors.iter_mut().enumerate() {
+        let normalization = if index == 0 { 1.0 } else { 2.0 };
+        for channel in factor.iter_mut() {
+            *channel *= normalization / pixel_count;
+        }
+    }
+
+    let dc = factors[0];
+    let ac = &factors[1..];
+
+    let mut hash = String::new();
+    hash.push_str(&encode_base83(
+        (COMPONENTS_X - 1) + (COMPONENTS_Y - 1) * 9,
+        1,
+    ));
+
+    let actual_max_ac = ac
+        .iter()
+        .flatten()
+        .fold(0.0f64, |max, &value| max.max(value.abs()));
+    let quantized_max_ac = (actual_max_ac * 166.0 - 0.5).round().clamp(0.0, 82.0);
+    hash.push_str(&encode_base83(quantized_max_ac as u32, 1));
+    // The quantized byte, not the raw maximum, is what a decoder reconstructs and uses to
+    // dequantize the AC components below, so encode against the same rounded value.
+    let max_ac = (quantized_max_ac + 1.0) / 166.0;
+
+    let dc_value = ((linear_to_srgb(dc[0]) as u32) << 16)
+        | ((linear_to_srgb(dc[1]) as u32) << 8)
+        | linear_to_srgb(dc[2]) as u32;
+    hash.push_str(&encode_base83(dc_value, 4));
+
+    for component in ac {
+        let quantize = |value: f64| -> u32 {
+            let normalized = (value / max_ac).signum() * (value / max_ac).abs().powf(0.5);
+            (normalized * 9.0 + 9.5).round().clamp(0.0, 18.0) as u32
+        };
+        let value =
+            quantize(component[0]) * 19 * 19 + quantize(component[1]) * 19 + quantize(component[2]);
+        hash.push_str(&encode_base83(value, 2));
+    }
+
+    hash
+}
+
+/// Builds a tiny JSON manifest listing each page's BlurHash, in page order.
+pub fn manifest(pages: &[PagePreview]) -> String {
+    let mut out = String::from("{\"pages\":[");
+    for (index, page) in pages.iter().enumerate() {
+        if index > 0 {
+            out.push(',');
+        }
+        out.push_str(&format!(
+            "{{\"name\":\"{}\",\"blurhash\":\"{}\"}}",
+            page.name.replace('\\', "\\\\").replace('"', "\\\""),
+            page.blurhash
+        ));
+    }
+    out.push_str("]}");
+    out
+}
+
+fn srgb_to_linear(value: u8) -> f64 {
+    let s = value as f64 / 255.0;
+    if s <= 0.04045 {
+        s / 12.92
+    } else {
+        ((s + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f64) -> u8 {
+    let value = value.clamp(0.0, 1.0);
+    let s = if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    };
+    (s * 255.0).round().clamp(0.0, 255.0) as u8
+}
+
+fn encode_base83(mut value: u32, length: usize) -> String {
+    let mut out = vec![0u8; length];
+    for slot in out.iter_mut().rev() {
+        *slot = BASE83_CHARS[(value % 83) as usize];
+        value /= 83;
+    }
+    String::from_utf8(out).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+
+    /// BlurHash strings are always 4 + 2 * (COMPONENTS_X * COMPONENTS_Y - 1) base83 characters:
+    /// 1 for the size flag, 1 for the quantized max AC, 4 for the DC, 2 per remaining AC component.
+    #[test]
+    fn hash_has_expected_length() {
+        let image = DynamicImage::ImageRgb8(image::RgbImage::from_pixel(8, 6, Rgb([120, 60, 200])));
+        let hash = blurhash(&image);
+        assert_eq!(hash.len(), 4 + 2 * (COMPONENTS_X * COMPONENTS_Y - 1) as usize);
+        assert!(hash.bytes().all(|b| BASE83_CHARS.contains(&b)));
+    }
+
+    #[test]
+    fn hash_is_deterministic() {
+        let image = DynamicImage::ImageRgb8(image::RgbImage::from_fn(12, 9, |x, y| {
+            Rgb([(x * 17) as u8, (y * 23) as u8, ((x + y) * 11) as u8])
+        }));
+        assert_eq!(blurhash(&image), blurhash(&image));
+    }
+
+    #[test]
+    fn distinct_images_hash_differently() {
+        let flat = DynamicImage::ImageRgb8(image::RgbImage::from_pixel(8, 8, Rgb([10, 10, 10])));
+        let gradient = DynamicImage::ImageRgb8(image::RgbImage::from_fn(8, 8, |x, y| {
+            Rgb([(x * 30) as u8, (y * 30) as u8, 0])
+        }));
+        assert_ne!(blurhash(&flat), blurhash(&gradient));
+    }
+
+    #[test]
+    fn thumbnail_never_upscales() {
+        let image = DynamicImage::ImageRgb8(image::RgbImage::from_pixel(4, 4, Rgb([0, 0, 0])));
+        let result = thumbnail(&image, 64);
+        assert_eq!(result.dimensions(), (4, 4));
+    }
+}